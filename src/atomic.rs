@@ -0,0 +1,134 @@
+//! Atomic, corruption-safe writes to the output directory.
+//!
+//! Every write goes into a temporary file beside its destination, is
+//! flushed to disk, then renamed over the destination in a single
+//! syscall — so a build interrupted mid-render (or a template error)
+//! never leaves a half-written file for a webserver watching `dist/` to
+//! serve. A write whose content is unchanged from what's already on disk
+//! never gets rewritten — but its mtime is still bumped to now, so the
+//! dependency-graph staleness check in `deps::is_up_to_date` sees it as
+//! freshly built instead of re-rendering it on every subsequent build.
+
+use crate::fs::Fs;
+use crate::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A simple, dependency-free content hash (FNV-1a). It only needs to tell
+/// "did this output change", not offer cryptographic guarantees.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn tmp_path(dst: &Path) -> PathBuf {
+    let n = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let file_name = dst.file_name().and_then(|f| f.to_str()).unwrap_or("out");
+    dst.with_file_name(format!("{file_name}.{}.{n}.tmp", process::id()))
+}
+
+/// Runs `write` into a fresh temp file beside `dst` and renames it over
+/// `dst` on success. If the parent directory doesn't exist yet, creates
+/// it and retries once.
+fn write_via_tmp(fs: &dyn Fs, dst: &Path, write: impl Fn(&Path) -> io::Result<()>) -> Result<(), Error> {
+    let tmp = tmp_path(dst);
+    match write(&tmp) {
+        Ok(()) => fs.rename(&tmp, dst).map_err(Error::Io),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let parent = dst.parent().ok_or_else(|| {
+                Error::Unexpected(format!("Parent dir could not be found: {}", dst.display()))
+            })?;
+            fs.create_dir(parent).map_err(Error::Io)?;
+            write(&tmp).map_err(Error::Io)?;
+            fs.rename(&tmp, dst).map_err(Error::Io)
+        }
+        Err(e) => Err(Error::Io(e)),
+    }
+}
+
+/// Atomically writes `bytes` to `dst`, skipping the write entirely if
+/// `dst` already holds the same content. The skip still bumps `dst`'s
+/// mtime to now, so a page whose rendered output is unchanged is
+/// recorded as freshly built rather than looking perpetually stale to
+/// `deps::is_up_to_date` next time one of its dependencies changes.
+pub fn write_bytes(fs: &dyn Fs, dst: &Path, bytes: &[u8]) -> Result<(), Error> {
+    if let Ok(existing) = fs.read(dst) {
+        if existing.len() == bytes.len() && fnv1a(&existing) == fnv1a(bytes) {
+            // Best-effort: if dst can't be touched (e.g. a read-only
+            // destination), the skip itself still succeeded — at worst
+            // this page gets needlessly re-rendered next build.
+            let _ = fs.touch(dst);
+            return Ok(());
+        }
+    }
+    write_via_tmp(fs, dst, |tmp| fs.create(tmp, bytes))
+}
+
+/// Atomically copies `src` to `dst`, skipping the copy if `dst` already
+/// matches `src`'s length and is at least as new.
+pub fn copy_file(fs: &dyn Fs, src: &Path, dst: &Path) -> Result<(), Error> {
+    let src_meta = fs.metadata(src).map_err(Error::Io)?;
+    if let Ok(dst_meta) = fs.metadata(dst) {
+        if src_meta.len == dst_meta.len && src_meta.modified <= dst_meta.modified {
+            return Ok(());
+        }
+    }
+    write_via_tmp(fs, dst, |tmp| fs.copy(src, tmp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::FakeFs;
+
+    #[test]
+    fn write_bytes_creates_a_new_file() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/dist");
+        write_bytes(&fsys, Path::new("/dist/out.html"), b"hello").unwrap();
+        assert_eq!(fsys.read(Path::new("/dist/out.html")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn write_bytes_skips_the_rewrite_but_still_bumps_mtime_when_content_is_unchanged() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/dist");
+        write_bytes(&fsys, Path::new("/dist/out.html"), b"hello").unwrap();
+        let first_mtime = fsys.metadata(Path::new("/dist/out.html")).unwrap().modified;
+
+        write_bytes(&fsys, Path::new("/dist/out.html"), b"hello").unwrap();
+        let second_mtime = fsys.metadata(Path::new("/dist/out.html")).unwrap().modified;
+
+        assert_eq!(fsys.read(Path::new("/dist/out.html")).unwrap(), b"hello");
+        assert!(second_mtime > first_mtime);
+    }
+
+    #[test]
+    fn write_bytes_rewrites_when_content_changes() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/dist");
+        write_bytes(&fsys, Path::new("/dist/out.html"), b"hello").unwrap();
+        write_bytes(&fsys, Path::new("/dist/out.html"), b"goodbye").unwrap();
+        assert_eq!(fsys.read(Path::new("/dist/out.html")).unwrap(), b"goodbye");
+    }
+
+    #[test]
+    fn copy_file_skips_when_dst_already_matches_len_and_is_at_least_as_new() {
+        let fsys = FakeFs::new();
+        fsys.add_file("/src/a.txt", "hi");
+        fsys.add_file("/dist/a.txt", "hi");
+        let mtime_before = fsys.metadata(Path::new("/dist/a.txt")).unwrap().modified;
+        copy_file(&fsys, Path::new("/src/a.txt"), Path::new("/dist/a.txt")).unwrap();
+        let mtime_after = fsys.metadata(Path::new("/dist/a.txt")).unwrap().modified;
+        assert_eq!(fsys.read(Path::new("/dist/a.txt")).unwrap(), b"hi");
+        assert_eq!(mtime_after, mtime_before);
+    }
+}
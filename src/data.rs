@@ -0,0 +1,215 @@
+//! Global site data and per-page front matter.
+//!
+//! An optional `data.toml`/`data.json`/`data.yaml` at the source root is
+//! loaded once and exposed to every template under the `site` namespace.
+//! Each page may additionally start with a `---`-delimited front-matter
+//! block (TOML or YAML), exposed as `page`, whose `permalink` key — if
+//! present — overrides the page's computed output path.
+//!
+//! Parsing itself is delegated to `toml`, `serde_json` and `serde_yaml`;
+//! [`Data`] is just the common shape we convert all three into so the
+//! rest of the crate doesn't need to care which format a given file was.
+
+use crate::fs::Fs;
+use crate::Error;
+use minijinja::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub enum Data {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Data>),
+    Table(BTreeMap<String, Data>),
+}
+
+impl Data {
+    pub fn empty_table() -> Data {
+        Data::Table(BTreeMap::new())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Data> {
+        match self {
+            Data::Table(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Data::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<Data> for Value {
+    fn from(data: Data) -> Value {
+        match data {
+            Data::Null => Value::from(Option::<i32>::None),
+            Data::Bool(b) => Value::from(b),
+            Data::Number(n) => Value::from(n),
+            Data::String(s) => Value::from(s),
+            Data::Array(items) => {
+                let values: Vec<Value> = items.into_iter().map(Value::from).collect();
+                Value::from(values)
+            }
+            Data::Table(map) => {
+                let values: BTreeMap<String, Value> =
+                    map.into_iter().map(|(k, v)| (k, Value::from(v))).collect();
+                Value::from(values)
+            }
+        }
+    }
+}
+
+impl From<toml::Value> for Data {
+    fn from(value: toml::Value) -> Data {
+        match value {
+            toml::Value::String(s) => Data::String(s),
+            toml::Value::Integer(n) => Data::Number(n as f64),
+            toml::Value::Float(n) => Data::Number(n),
+            toml::Value::Boolean(b) => Data::Bool(b),
+            toml::Value::Datetime(dt) => Data::String(dt.to_string()),
+            toml::Value::Array(items) => Data::Array(items.into_iter().map(Data::from).collect()),
+            toml::Value::Table(map) => {
+                Data::Table(map.into_iter().map(|(k, v)| (k, Data::from(v))).collect())
+            }
+        }
+    }
+}
+
+impl From<serde_json::Value> for Data {
+    fn from(value: serde_json::Value) -> Data {
+        match value {
+            serde_json::Value::Null => Data::Null,
+            serde_json::Value::Bool(b) => Data::Bool(b),
+            serde_json::Value::Number(n) => Data::Number(n.as_f64().unwrap_or(0.0)),
+            serde_json::Value::String(s) => Data::String(s),
+            serde_json::Value::Array(items) => {
+                Data::Array(items.into_iter().map(Data::from).collect())
+            }
+            serde_json::Value::Object(map) => {
+                Data::Table(map.into_iter().map(|(k, v)| (k, Data::from(v))).collect())
+            }
+        }
+    }
+}
+
+impl From<serde_yaml::Value> for Data {
+    fn from(value: serde_yaml::Value) -> Data {
+        match value {
+            serde_yaml::Value::Null => Data::Null,
+            serde_yaml::Value::Bool(b) => Data::Bool(b),
+            serde_yaml::Value::Number(n) => Data::Number(n.as_f64().unwrap_or(0.0)),
+            serde_yaml::Value::String(s) => Data::String(s),
+            serde_yaml::Value::Sequence(items) => {
+                Data::Array(items.into_iter().map(Data::from).collect())
+            }
+            serde_yaml::Value::Mapping(map) => Data::Table(
+                map.into_iter()
+                    .map(|(k, v)| (yaml_key_to_string(k), Data::from(v)))
+                    .collect(),
+            ),
+            serde_yaml::Value::Tagged(tagged) => Data::from(tagged.value),
+        }
+    }
+}
+
+fn yaml_key_to_string(key: serde_yaml::Value) -> String {
+    match key {
+        serde_yaml::Value::String(s) => s,
+        serde_yaml::Value::Bool(b) => b.to_string(),
+        serde_yaml::Value::Number(n) => n.to_string(),
+        other => serde_yaml::to_string(&other)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
+/// The first of `data.toml`/`data.json`/`data.yaml`/`data.yml` that
+/// exists under `src_dir`, in that order — the same precedence
+/// `load_site_data` uses. `None` when no site data file is present.
+pub fn site_data_path(fsys: &dyn Fs, src_dir: &Path) -> Option<PathBuf> {
+    ["data.toml", "data.json", "data.yaml", "data.yml"]
+        .into_iter()
+        .map(|name| src_dir.join(name))
+        .find(|path| fsys.try_exists(path).unwrap_or(false))
+}
+
+/// Loads `data.toml`, `data.json`, `data.yaml` or `data.yml` from
+/// `src_dir`, whichever is found first, in that order. Returns an empty
+/// table when none of them exist.
+pub fn load_site_data(fsys: &dyn Fs, src_dir: &Path) -> Result<Data, Error> {
+    let candidates: [&str; 4] = ["data.toml", "data.json", "data.yaml", "data.yml"];
+    for name in candidates {
+        let path = src_dir.join(name);
+        let Ok(bytes) = fsys.read(&path) else {
+            continue;
+        };
+        let data = String::from_utf8(bytes)
+            .map_err(|e| e.utf8_error().to_string())
+            .and_then(|contents| {
+                if name == "data.toml" {
+                    toml::from_str::<toml::Value>(&contents)
+                        .map(Data::from)
+                        .map_err(|e| e.to_string())
+                } else if name == "data.json" {
+                    serde_json::from_str::<serde_json::Value>(&contents)
+                        .map(Data::from)
+                        .map_err(|e| e.to_string())
+                } else {
+                    serde_yaml::from_str::<serde_yaml::Value>(&contents)
+                        .map(Data::from)
+                        .map_err(|e| e.to_string())
+                }
+            });
+        return data.map_err(|message| Error::DataParse { path, message });
+    }
+    Ok(Data::empty_table())
+}
+
+/// Splits a page's source into an optional raw front-matter block and
+/// the remaining template body. Front matter is delimited by a `---`
+/// line at the very start of the file and a matching `---` line that
+/// ends it.
+pub fn split_front_matter(source: &str) -> (Option<&str>, &str) {
+    let Some(after_first) = source.strip_prefix("---\n") else {
+        return (None, source);
+    };
+    let Some(end) = after_first.find("\n---") else {
+        return (None, source);
+    };
+    let front_matter = &after_first[..end];
+    let rest = &after_first[end + "\n---".len()..];
+    let body = rest.strip_prefix('\n').unwrap_or(rest);
+    (Some(front_matter), body)
+}
+
+/// Parses a page's front-matter block (already stripped of its `---`
+/// delimiters) as TOML or YAML, trying TOML first since it's the
+/// stricter grammar of the two (valid TOML is rarely valid YAML, but
+/// the reverse isn't true).
+pub fn parse_front_matter(path: &Path, raw: &str) -> Result<Data, Error> {
+    parse_data_block(raw).map_err(|message| Error::DataParse {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+fn parse_data_block(raw: &str) -> Result<Data, String> {
+    match toml::from_str::<toml::Value>(raw) {
+        Ok(value) => Ok(Data::from(value)),
+        Err(toml_err) => serde_yaml::from_str::<serde_yaml::Value>(raw)
+            .map(Data::from)
+            .map_err(|yaml_err| {
+                format!(
+                    "not valid TOML ({toml_err}) or YAML ({yaml_err})"
+                )
+            }),
+    }
+}
@@ -0,0 +1,265 @@
+//! Template dependency graph.
+//!
+//! Tracks which partials a page pulls in via `{% extends %}`,
+//! `{% include %}` and `{% import %}`, so a build can detect include
+//! cycles up front (instead of blowing the stack inside minijinja) and
+//! can skip re-rendering pages whose page source and transitive partial
+//! dependencies haven't changed since the last build.
+
+use crate::fs::{FileKind, Fs};
+use crate::Error;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Recursively collects every `*.jinja` file under `dir`, skipping the
+/// output directory and VCS metadata. Unlike [`crate::get_input_files`],
+/// this ignores `.gitignore`/`.touchdownignore` rules and the leading-`_`
+/// convention, since a partial still participates in the dependency graph
+/// even when it's excluded from the page/file walk.
+pub fn collect_templates(fsys: &dyn Fs, dir: &Path) -> Vec<PathBuf> {
+    let mut result = Vec::new();
+    collect_templates_into(fsys, dir, &mut result);
+    result
+}
+
+fn collect_templates_into(fsys: &dyn Fs, dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = fsys.read_dir(dir) else {
+        return;
+    };
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == "dist" || name.starts_with(".git") {
+            continue;
+        }
+        if entry.kind == FileKind::Dir {
+            collect_templates_into(fsys, &entry.path, out);
+        } else if name.ends_with(".jinja") {
+            out.push(entry.path);
+        }
+    }
+}
+
+/// Scans a template's source for `{% extends "x" %}`, `{% include "x" %}`
+/// and `{% import "x" as y %}` tags, returning the referenced template
+/// names as written (relative to the source root).
+fn scan_imports(contents: &str) -> Vec<String> {
+    let mut imports = Vec::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find("{%") {
+        let tag_start = start + 2;
+        let Some(end) = rest[tag_start..].find("%}") else {
+            break;
+        };
+        let tag = rest[tag_start..tag_start + end]
+            .trim()
+            .trim_start_matches('-')
+            .trim_start_matches('+')
+            .trim();
+        for kw in ["extends", "include", "import"] {
+            if let Some(after) = tag.strip_prefix(kw) {
+                if let Some(name) = extract_string_literal(after) {
+                    imports.push(name);
+                }
+            }
+        }
+        rest = &rest[tag_start + end + 2..];
+    }
+    imports
+}
+
+fn extract_string_literal(s: &str) -> Option<String> {
+    let s = s.trim_start();
+    let quote = s.chars().next()?;
+    if quote != '"' && quote != '\'' {
+        return None;
+    }
+    let rest = &s[quote.len_utf8()..];
+    let end = rest.find(quote)?;
+    Some(rest[..end].to_string())
+}
+
+/// The resolved `template -> direct dependencies` edge set for a source
+/// tree.
+pub struct DepGraph {
+    edges: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl DepGraph {
+    /// Scans every template in `templates` for its direct includes and
+    /// resolves them relative to `src_dir`, then walks the resulting
+    /// graph to detect cycles.
+    pub fn build(fsys: &dyn Fs, src_dir: &Path, templates: &[PathBuf]) -> Result<DepGraph, Error> {
+        let mut edges = HashMap::new();
+        for tmpl in templates {
+            let bytes = fsys.read(tmpl).map_err(Error::Io)?;
+            let contents = String::from_utf8(bytes)
+                .map_err(|e| Error::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+            let deps = scan_imports(&contents)
+                .into_iter()
+                .map(|name| src_dir.join(name))
+                .filter(|p| fsys.try_exists(p).unwrap_or(false))
+                .collect();
+            edges.insert(tmpl.clone(), deps);
+        }
+        let graph = DepGraph { edges };
+        for tmpl in templates {
+            let mut chain = Vec::new();
+            graph.check_cycles(tmpl, &mut chain)?;
+        }
+        Ok(graph)
+    }
+
+    fn check_cycles(&self, path: &Path, chain: &mut Vec<PathBuf>) -> Result<(), Error> {
+        if chain.iter().any(|p| p == path) {
+            return Err(Error::CircularImport {
+                current: chain.last().cloned().unwrap_or_else(|| path.to_path_buf()),
+                import: path.to_path_buf(),
+            });
+        }
+        chain.push(path.to_path_buf());
+        if let Some(deps) = self.edges.get(path) {
+            for dep in deps {
+                self.check_cycles(dep, chain)?;
+            }
+        }
+        chain.pop();
+        Ok(())
+    }
+
+    /// All transitive dependencies of `path` (partials it extends,
+    /// includes or imports, directly or indirectly).
+    fn transitive_deps(&self, path: &Path) -> Vec<PathBuf> {
+        let mut seen = Vec::new();
+        self.collect(path, &mut seen);
+        seen
+    }
+
+    fn collect(&self, path: &Path, seen: &mut Vec<PathBuf>) {
+        if let Some(deps) = self.edges.get(path) {
+            for dep in deps {
+                if !seen.contains(dep) {
+                    seen.push(dep.clone());
+                    self.collect(dep, seen);
+                }
+            }
+        }
+    }
+}
+
+/// Returns true when `output_path` already exists and is at least as new
+/// as `page`, every one of its transitive partial dependencies, and the
+/// site data file (if any) — since every page template can read `site`,
+/// a `data.toml`/`.json`/`.yaml` edit invalidates all of them, not just
+/// the ones that mention `site` explicitly.
+pub fn is_up_to_date(
+    fsys: &dyn Fs,
+    page: &Path,
+    graph: &DepGraph,
+    output_path: &Path,
+    site_data_path: Option<&Path>,
+) -> bool {
+    let Ok(output_mtime) = fsys.metadata(output_path).map(|m| m.modified) else {
+        return false;
+    };
+    let Ok(mut newest) = fsys.metadata(page).map(|m| m.modified) else {
+        return false;
+    };
+    for dep in graph.transitive_deps(page) {
+        if let Ok(dep_mtime) = fsys.metadata(&dep).map(|m| m.modified) {
+            newest = newest.max(dep_mtime);
+        }
+    }
+    if let Some(site_data_path) = site_data_path {
+        if let Ok(site_data_mtime) = fsys.metadata(site_data_path).map(|m| m.modified) {
+            newest = newest.max(site_data_mtime);
+        }
+    }
+    newest <= output_mtime
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::FakeFs;
+
+    #[test]
+    fn collect_templates_walks_a_fakefs_tree_skipping_dist_and_git() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/src");
+        fsys.add_dir("/src/dist");
+        fsys.add_file("/src/dist/old.html", "stale");
+        fsys.add_dir("/src/partials");
+        fsys.add_file("/src/partials/_nav.jinja", "nav");
+        fsys.add_file("/src/page.html.jinja", "<h1>hi</h1>");
+        fsys.add_dir("/src/.git");
+        fsys.add_file("/src/.git/config", "ignored");
+
+        let mut templates = collect_templates(&fsys, Path::new("/src"));
+        templates.sort();
+
+        assert_eq!(
+            templates,
+            vec![
+                PathBuf::from("/src/page.html.jinja"),
+                PathBuf::from("/src/partials/_nav.jinja"),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_detects_a_circular_import() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/src");
+        fsys.add_file("/src/a.jinja", "{% extends \"b.jinja\" %}");
+        fsys.add_file("/src/b.jinja", "{% extends \"a.jinja\" %}");
+        let templates = vec![PathBuf::from("/src/a.jinja"), PathBuf::from("/src/b.jinja")];
+
+        let result = DepGraph::build(&fsys, Path::new("/src"), &templates);
+
+        assert!(matches!(result, Err(Error::CircularImport { .. })));
+    }
+
+    #[test]
+    fn is_up_to_date_goes_stale_when_a_partial_dependency_changes() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/src");
+        fsys.add_file("/src/_partial.jinja", "partial v1");
+        fsys.add_file("/src/page.html.jinja", "{% include \"_partial.jinja\" %}");
+        let templates = vec![
+            PathBuf::from("/src/page.html.jinja"),
+            PathBuf::from("/src/_partial.jinja"),
+        ];
+        let graph = DepGraph::build(&fsys, Path::new("/src"), &templates).unwrap();
+        fsys.add_file("/src/dist/page.html", "already rendered");
+        let page = Path::new("/src/page.html.jinja");
+        let output = Path::new("/src/dist/page.html");
+
+        assert!(is_up_to_date(&fsys, page, &graph, output, None));
+
+        fsys.add_file("/src/_partial.jinja", "partial v2");
+
+        assert!(!is_up_to_date(&fsys, page, &graph, output, None));
+    }
+
+    #[test]
+    fn is_up_to_date_goes_stale_when_the_site_data_file_changes() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/src");
+        fsys.add_file("/src/page.html.jinja", "<h1>hi</h1>");
+        fsys.add_file("/src/data.toml", "title = \"v1\"");
+        let templates = vec![PathBuf::from("/src/page.html.jinja")];
+        let graph = DepGraph::build(&fsys, Path::new("/src"), &templates).unwrap();
+        fsys.add_file("/src/dist/page.html", "already rendered");
+        let page = Path::new("/src/page.html.jinja");
+        let output = Path::new("/src/dist/page.html");
+        let site_data = Path::new("/src/data.toml");
+
+        assert!(is_up_to_date(&fsys, page, &graph, output, Some(site_data)));
+
+        fsys.add_file("/src/data.toml", "title = \"v2\"");
+
+        assert!(!is_up_to_date(&fsys, page, &graph, output, Some(site_data)));
+    }
+}
@@ -0,0 +1,322 @@
+//! Filesystem abstraction.
+//!
+//! `get_input_files`, `render_page`, `copy_file` and `copy_dir_recursive`
+//! talk to the filesystem through the [`Fs`] trait. [`RealFs`]
+//! forwards to `std::fs` for actual builds; [`FakeFs`] keeps an in-memory
+//! tree so the walking/rendering/copying logic can be unit-tested without
+//! touching disk. Two reads bypass the trait: `.gitignore` and
+//! `.touchdownignore` files are read with `std::fs` by
+//! `ignore::IgnoreLayer::load`, and templates that a page extends,
+//! includes or imports are loaded from disk by minijinja's `path_loader`.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub path: PathBuf,
+    pub kind: FileKind,
+}
+
+impl DirEntry {
+    pub fn file_name(&self) -> std::ffi::OsString {
+        self.path
+            .file_name()
+            .map(|n| n.to_os_string())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub len: u64,
+    pub modified: SystemTime,
+}
+
+pub trait Fs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>>;
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+    fn create_dir(&self, dir: &Path) -> io::Result<()>;
+    /// Creates (or truncates) `path` and writes `bytes` to it.
+    fn create(&self, path: &Path, bytes: &[u8]) -> io::Result<()>;
+    fn copy(&self, src: &Path, dst: &Path) -> io::Result<()>;
+    fn rename(&self, src: &Path, dst: &Path) -> io::Result<()>;
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    fn try_exists(&self, path: &Path) -> io::Result<bool>;
+    fn is_file(&self, path: &Path) -> bool;
+    fn is_dir(&self, path: &Path) -> bool;
+    /// Bumps `path`'s modified time to now, without touching its content.
+    fn touch(&self, path: &Path) -> io::Result<()>;
+}
+
+/// `Fs` implementation backed by `std::fs`, used for real builds.
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        std::fs::read_dir(dir)?
+            .map(|entry| {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let kind = if file_type.is_symlink() {
+                    FileKind::Symlink
+                } else if file_type.is_dir() {
+                    FileKind::Dir
+                } else {
+                    FileKind::File
+                };
+                Ok(DirEntry {
+                    path: entry.path(),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let meta = std::fs::metadata(path)?;
+        Ok(Metadata {
+            len: meta.len(),
+            modified: meta.modified()?,
+        })
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        std::fs::canonicalize(path)
+    }
+
+    fn create_dir(&self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir(dir)
+    }
+
+    fn create(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        use std::io::Write;
+        let mut file = std::fs::File::create(path)?;
+        file.write_all(bytes)?;
+        file.sync_all()
+    }
+
+    fn copy(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        std::fs::copy(src, dst)?;
+        std::fs::File::open(dst)?.sync_all()
+    }
+
+    fn rename(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        std::fs::rename(src, dst)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn try_exists(&self, path: &Path) -> io::Result<bool> {
+        path.try_exists()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn touch(&self, path: &Path) -> io::Result<()> {
+        std::fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(SystemTime::now())
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)] // only reachable from the cfg(test) FakeFs-based tests, not the real binary
+enum Entry {
+    File { bytes: Vec<u8>, modified: u64 },
+    Dir,
+    Symlink { target: PathBuf },
+}
+
+/// In-memory `Fs` for tests, with interior mutability so writes made
+/// through the `&dyn Fs` the production code expects still land. The
+/// logical clock `tick` (bumped on every write) stands in for
+/// "modified", keeping behaviour deterministic without the system clock.
+#[derive(Debug, Default)]
+#[allow(dead_code)] // only reachable from the cfg(test) FakeFs-based tests, not the real binary
+pub struct FakeFs {
+    entries: RefCell<BTreeMap<PathBuf, Entry>>,
+    tick: RefCell<u64>,
+}
+
+#[allow(dead_code)] // only reachable from the cfg(test) FakeFs-based tests, not the real binary
+impl FakeFs {
+    pub fn new() -> FakeFs {
+        FakeFs::default()
+    }
+
+    fn logical_time(tick: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(tick)
+    }
+
+    fn next_tick(&self) -> u64 {
+        let mut tick = self.tick.borrow_mut();
+        *tick += 1;
+        *tick
+    }
+
+    pub fn add_dir(&self, path: impl Into<PathBuf>) {
+        self.entries.borrow_mut().insert(path.into(), Entry::Dir);
+    }
+
+    pub fn add_file(&self, path: impl Into<PathBuf>, bytes: impl Into<Vec<u8>>) {
+        let modified = self.next_tick();
+        self.entries.borrow_mut().insert(
+            path.into(),
+            Entry::File {
+                bytes: bytes.into(),
+                modified,
+            },
+        );
+    }
+
+    pub fn add_symlink(&self, path: impl Into<PathBuf>, target: impl Into<PathBuf>) {
+        self.entries.borrow_mut().insert(
+            path.into(),
+            Entry::Symlink {
+                target: target.into(),
+            },
+        );
+    }
+
+    fn resolve(&self, path: &Path) -> io::Result<Entry> {
+        self.entries
+            .borrow()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.display().to_string()))
+    }
+}
+
+impl Fs for FakeFs {
+    fn read_dir(&self, dir: &Path) -> io::Result<Vec<DirEntry>> {
+        if !matches!(self.resolve(dir)?, Entry::Dir) {
+            return Err(io::Error::other(format!(
+                "{} is not a directory",
+                dir.display()
+            )));
+        }
+        let mut result = vec![];
+        for (path, entry) in self.entries.borrow().iter() {
+            if path.parent() == Some(dir) && path != dir {
+                let kind = match entry {
+                    Entry::File { .. } => FileKind::File,
+                    Entry::Dir => FileKind::Dir,
+                    Entry::Symlink { .. } => FileKind::Symlink,
+                };
+                result.push(DirEntry {
+                    path: path.clone(),
+                    kind,
+                });
+            }
+        }
+        Ok(result)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        match self.resolve(path)? {
+            Entry::File { bytes, modified } => Ok(Metadata {
+                len: bytes.len() as u64,
+                modified: FakeFs::logical_time(modified),
+            }),
+            Entry::Dir => Ok(Metadata {
+                len: 0,
+                modified: FakeFs::logical_time(0),
+            }),
+            Entry::Symlink { target } => self.metadata(&target),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        match self.resolve(path)? {
+            Entry::Symlink { target } => self.canonicalize(&target),
+            _ => Ok(path.to_path_buf()),
+        }
+    }
+
+    fn create_dir(&self, dir: &Path) -> io::Result<()> {
+        self.add_dir(dir.to_path_buf());
+        Ok(())
+    }
+
+    fn create(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        self.add_file(path.to_path_buf(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn copy(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        let bytes = self.read(src)?;
+        self.add_file(dst.to_path_buf(), bytes);
+        Ok(())
+    }
+
+    fn rename(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        let entry = self.resolve(src)?;
+        self.entries.borrow_mut().remove(src);
+        self.entries.borrow_mut().insert(dst.to_path_buf(), entry);
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        match self.resolve(path)? {
+            Entry::File { bytes, .. } => Ok(bytes),
+            Entry::Symlink { target } => self.read(&target),
+            Entry::Dir => Err(io::Error::other(format!(
+                "{} is a directory",
+                path.display()
+            ))),
+        }
+    }
+
+    fn try_exists(&self, path: &Path) -> io::Result<bool> {
+        Ok(self.entries.borrow().contains_key(path))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        match self.resolve(path) {
+            Ok(Entry::File { .. }) => true,
+            Ok(Entry::Symlink { target }) => self.is_file(&target),
+            _ => false,
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        match self.resolve(path) {
+            Ok(Entry::Dir) => true,
+            Ok(Entry::Symlink { target }) => self.is_dir(&target),
+            _ => false,
+        }
+    }
+
+    fn touch(&self, path: &Path) -> io::Result<()> {
+        let tick = self.next_tick();
+        match self.entries.borrow_mut().get_mut(path) {
+            Some(Entry::File { modified, .. }) => {
+                *modified = tick;
+                Ok(())
+            }
+            Some(_) => Err(io::Error::other(format!("{} is not a file", path.display()))),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, path.display().to_string())),
+        }
+    }
+}
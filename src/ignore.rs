@@ -0,0 +1,254 @@
+//! Gitignore-style exclusion matching used while walking the source tree.
+//!
+//! Patterns are compiled per-directory (one [`IgnoreLayer`] per
+//! `.gitignore`/`.touchdownignore` found while descending) and kept on an
+//! [`IgnoreStack`], modeled on how the `ignore` crate resolves precedence:
+//! a pattern from a deeper directory overrides one from a shallower
+//! directory, and within a single file the last matching line wins over
+//! earlier ones.
+
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    negated: bool,
+    anchored: bool,
+    dir_only: bool,
+    segments: Vec<String>,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let mut rest = line;
+        let negated = if let Some(stripped) = rest.strip_prefix('!') {
+            rest = stripped;
+            true
+        } else {
+            false
+        };
+        let dir_only = rest.len() > 1 && rest.ends_with('/');
+        if dir_only {
+            rest = &rest[..rest.len() - 1];
+        }
+        let anchored = rest.starts_with('/');
+        let rest = rest.trim_start_matches('/');
+        if rest.is_empty() {
+            return None;
+        }
+        let segments = rest.split('/').map(str::to_string).collect();
+        Some(Pattern {
+            negated,
+            anchored,
+            dir_only,
+            segments,
+        })
+    }
+
+    /// `rel_segments` is the candidate path relative to the directory this
+    /// pattern was loaded from.
+    fn matches(&self, rel_segments: &[&str], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored || self.segments.len() > 1 {
+            match_segments(&self.segments, rel_segments)
+        } else {
+            // A single unanchored segment may match starting at any depth.
+            (0..rel_segments.len()).any(|i| match_segments(&self.segments, &rel_segments[i..]))
+        }
+    }
+}
+
+fn match_segments(pattern: &[String], path: &[&str]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (None, Some(_)) => false,
+        (Some(p), _) if p == "**" => {
+            if pattern.len() == 1 {
+                return true;
+            }
+            (0..=path.len()).any(|i| match_segments(&pattern[1..], &path[i..]))
+        }
+        (Some(_), None) => false,
+        (Some(p), Some(seg)) => glob_match(p, seg) && match_segments(&pattern[1..], &path[1..]),
+    }
+}
+
+/// Shell-style glob match for a single path segment: `*`, `?`, and
+/// `[...]` character classes (with `!`/`^` negation and `a-z` ranges).
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    glob_match_rec(&p, &t)
+}
+
+fn glob_match_rec(p: &[char], t: &[char]) -> bool {
+    match p.first() {
+        None => t.is_empty(),
+        Some('*') => (0..=t.len()).any(|i| glob_match_rec(&p[1..], &t[i..])),
+        Some('?') => !t.is_empty() && glob_match_rec(&p[1..], &t[1..]),
+        Some('[') => match p.iter().position(|&c| c == ']') {
+            Some(close) if close > 1 => {
+                if t.is_empty() {
+                    return false;
+                }
+                let class = &p[1..close];
+                let (negate, class) = match class.first() {
+                    Some('!') | Some('^') => (true, &class[1..]),
+                    _ => (false, class),
+                };
+                (class_matches(class, t[0]) != negate) && glob_match_rec(&p[close + 1..], &t[1..])
+            }
+            _ => !t.is_empty() && p[0] == t[0] && glob_match_rec(&p[1..], &t[1..]),
+        },
+        Some(c) => !t.is_empty() && *c == t[0] && glob_match_rec(&p[1..], &t[1..]),
+    }
+}
+
+fn class_matches(class: &[char], ch: char) -> bool {
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            if ch >= class[i] && ch <= class[i + 2] {
+                return true;
+            }
+            i += 3;
+        } else {
+            if class[i] == ch {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+/// The patterns contributed by the ignore file(s) found in one directory,
+/// in file order (a later line overrides an earlier one when both match
+/// the same path).
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreLayer {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreLayer {
+    fn from_lines(contents: &str) -> IgnoreLayer {
+        IgnoreLayer {
+            patterns: contents.lines().filter_map(Pattern::parse).collect(),
+        }
+    }
+
+    /// Loads `.gitignore` and `.touchdownignore` directly inside `dir`, if
+    /// present. `.touchdownignore` patterns are appended after
+    /// `.gitignore`'s, so they're consulted last and take precedence.
+    pub fn load(dir: &Path) -> IgnoreLayer {
+        let mut patterns = Vec::new();
+        if let Ok(contents) = fs::read_to_string(dir.join(".gitignore")) {
+            patterns.extend(IgnoreLayer::from_lines(&contents).patterns);
+        }
+        if let Ok(contents) = fs::read_to_string(dir.join(".touchdownignore")) {
+            patterns.extend(IgnoreLayer::from_lines(&contents).patterns);
+        }
+        IgnoreLayer { patterns }
+    }
+
+    /// touchdown's built-in skips, expressed as ordinary (overridable)
+    /// patterns rather than hardcoded filename checks. `.git*` covers
+    /// `.git`, `.gitignore`, `.gitattributes`, etc., and
+    /// `.touchdownignore` is listed explicitly since it's this feature's
+    /// own config file and shouldn't get published to `dist/` either.
+    pub fn defaults() -> IgnoreLayer {
+        IgnoreLayer::from_lines("dist\n*~\n_*\n.git*\n.touchdownignore\n")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    fn last_match(&self, rel_segments: &[&str], is_dir: bool) -> Option<&Pattern> {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|p| p.matches(rel_segments, is_dir))
+    }
+}
+
+/// A stack of [`IgnoreLayer`]s accumulated while descending the source
+/// tree, each tagged with the depth (in path components from the source
+/// root) of the directory it was loaded from.
+#[derive(Debug, Default)]
+pub struct IgnoreStack {
+    layers: Vec<(usize, IgnoreLayer)>,
+}
+
+impl IgnoreStack {
+    pub fn new() -> IgnoreStack {
+        IgnoreStack {
+            layers: vec![(0, IgnoreLayer::defaults())],
+        }
+    }
+
+    pub fn push(&mut self, depth: usize, layer: IgnoreLayer) {
+        self.layers.push((depth, layer));
+    }
+
+    pub fn pop(&mut self) {
+        self.layers.pop();
+    }
+
+    /// Tests `rel_segments` (the candidate path relative to the source
+    /// root) against every layer, most recently pushed (i.e. deepest)
+    /// first. Returns true when the most specific matching pattern is not
+    /// a negation, and false when it is a negation or no pattern matches.
+    pub fn is_ignored(&self, rel_segments: &[&str], is_dir: bool) -> bool {
+        for (depth, layer) in self.layers.iter().rev() {
+            if let Some(pattern) = layer.last_match(&rel_segments[*depth..], is_dir) {
+                return !pattern.negated;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negation_overrides_an_earlier_broader_pattern() {
+        let mut stack = IgnoreStack::new();
+        stack.push(0, IgnoreLayer::from_lines("*.log\n!keep.log\n"));
+        assert!(stack.is_ignored(&["app.log"], false));
+        assert!(!stack.is_ignored(&["keep.log"], false));
+    }
+
+    #[test]
+    fn anchored_pattern_only_matches_at_the_directory_it_was_loaded_from() {
+        let mut stack = IgnoreStack::new();
+        stack.push(0, IgnoreLayer::from_lines("/build\n"));
+        assert!(stack.is_ignored(&["build"], true));
+        assert!(!stack.is_ignored(&["nested", "build"], true));
+    }
+
+    #[test]
+    fn dir_only_pattern_does_not_match_a_plain_file_of_the_same_name() {
+        let mut stack = IgnoreStack::new();
+        stack.push(0, IgnoreLayer::from_lines("build/\n"));
+        assert!(stack.is_ignored(&["build"], true));
+        assert!(!stack.is_ignored(&["build"], false));
+    }
+
+    #[test]
+    fn a_deeper_layer_takes_precedence_over_a_shallower_one() {
+        let mut stack = IgnoreStack::new();
+        stack.push(0, IgnoreLayer::from_lines("*.log\n"));
+        stack.push(1, IgnoreLayer::from_lines("!debug.log\n"));
+        assert!(!stack.is_ignored(&["sub", "debug.log"], false));
+        assert!(stack.is_ignored(&["other.log"], false));
+    }
+}
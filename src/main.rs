@@ -1,8 +1,16 @@
+mod atomic;
+mod data;
+mod deps;
+mod fs;
+mod ignore;
+
 use core::fmt;
-use minijinja::{context, path_loader, Environment};
+use data::Data;
+use fs::{FileKind, Fs, RealFs};
+use ignore::{IgnoreLayer, IgnoreStack};
+use minijinja::{context, path_loader, Environment, Value};
 use std::fmt::Display;
-use std::fs::{self, File};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::{env, io, process};
 
 #[derive(Debug)]
@@ -11,6 +19,9 @@ enum Error {
     Minijinja(minijinja::Error),
     StripPrefix(std::path::StripPrefixError),
     Unexpected(String),
+    CircularImport { current: PathBuf, import: PathBuf },
+    DataParse { path: PathBuf, message: String },
+    InvalidPermalink { path: PathBuf, permalink: String },
 }
 
 impl Display for Error {
@@ -20,6 +31,20 @@ impl Display for Error {
             Self::Minijinja(e) => write!(f, "Minijinja error: {e}"),
             Self::StripPrefix(e) => write!(f, "StripPrefixError: {e}"),
             Self::Unexpected(e) => write!(f, "Unexpected error: {e}"),
+            Self::CircularImport { current, import } => write!(
+                f,
+                "Circular import detected: {} imports {}, which is already in the include chain",
+                current.display(),
+                import.display()
+            ),
+            Self::DataParse { path, message } => {
+                write!(f, "Failed to parse data in {}: {message}", path.display())
+            }
+            Self::InvalidPermalink { path, permalink } => write!(
+                f,
+                "Invalid permalink {permalink:?} in {}: must not escape the output directory",
+                path.display()
+            ),
         }
     }
 }
@@ -35,71 +60,88 @@ fn is_page(filename: &str) -> bool {
     filename.ends_with(".html.jinja")
 }
 
-// @TODO: Allow user specified exclusions
-fn must_skip(filename: &str) -> bool {
-    filename.starts_with(".git")       // the git repo, .gitignore etc. files
-        || filename == "dist"          // the output directory
-        || filename.ends_with('~')     // emacs backup files
-        || filename.starts_with('_') // included jinja templates
-}
+fn get_input_files(
+    fsys: &dyn Fs,
+    base_dir: &Path,
+    src_dir: &Path,
+    ignores: &mut IgnoreStack,
+) -> Result<Vec<InputFile>, Error> {
+    let depth = base_dir
+        .strip_prefix(src_dir)
+        .map(|p| p.components().count())
+        .unwrap_or(0);
+    let layer = IgnoreLayer::load(base_dir);
+    let pushed = !layer.is_empty();
+    if pushed {
+        ignores.push(depth, layer);
+    }
 
-fn get_input_files(base_dir: &Path) -> Result<Vec<InputFile>, Error> {
     let mut result = vec![];
-    for member in fs::read_dir(base_dir).map_err(Error::Io)? {
-        let entry = member.map_err(Error::Io)?;
+    for entry in fsys.read_dir(base_dir).map_err(Error::Io)? {
         let filename = entry.file_name();
         // Assuming filenames are valid utf-8
         let filename_lossy = filename.to_string_lossy();
-        if must_skip(&filename_lossy) {
+        let rel_path = entry
+            .path
+            .strip_prefix(src_dir)
+            .map_err(Error::StripPrefix)?
+            .to_path_buf();
+        let rel_owned: Vec<String> = rel_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let rel_segments: Vec<&str> = rel_owned.iter().map(String::as_str).collect();
+        if ignores.is_ignored(&rel_segments, entry.kind == FileKind::Dir) {
             // @TODO: Replace with a log line
             // println!("Ignoring entry: {entry:?}");
             continue;
         }
         if is_page(&filename_lossy) {
-            result.push(InputFile::Page(entry.path()));
-        } else {
-            let filetype = entry.file_type().map_err(Error::Io)?;
-            if filetype.is_dir() {
-                for nested_file in get_input_files(&entry.path())? {
-                    result.push(nested_file);
-                }
-            } else if filetype.is_file() {
-                result.push(InputFile::File(entry.path()));
-            } else if filetype.is_symlink() {
-                let target = entry.path().canonicalize().map_err(Error::Io)?;
-                // @NOTE: Here we're checking whether the symlink
-                // target is a file or a dir, but the original symlink
-                // itself is being added to the result. That is
-                // because at the time of copying files, we need the
-                // symlink path to be able to find path relative to
-                // the src/input dir.
-                if target.is_file() {
-                    result.push(InputFile::File(entry.path()));
-                } else if target.is_dir() {
-                    result.push(InputFile::Dir(entry.path()));
-                } else {
-                    panic!("unexpected condition met");
-                }
+            result.push(InputFile::Page(entry.path));
+        } else if entry.kind == FileKind::Dir {
+            for nested_file in get_input_files(fsys, &entry.path, src_dir, ignores)? {
+                result.push(nested_file);
+            }
+        } else if entry.kind == FileKind::File {
+            result.push(InputFile::File(entry.path));
+        } else if entry.kind == FileKind::Symlink {
+            let target = fsys.canonicalize(&entry.path).map_err(Error::Io)?;
+            // @NOTE: Here we're checking whether the symlink
+            // target is a file or a dir, but the original symlink
+            // itself is being added to the result. That is
+            // because at the time of copying files, we need the
+            // symlink path to be able to find path relative to
+            // the src/input dir.
+            if fsys.is_file(&target) {
+                result.push(InputFile::File(entry.path));
+            } else if fsys.is_dir(&target) {
+                result.push(InputFile::Dir(entry.path));
+            } else {
+                panic!("unexpected condition met");
             }
         }
     }
+
+    if pushed {
+        ignores.pop();
+    }
     Ok(result)
 }
 
-fn ensure_dir(dir: &Path) -> Result<(), io::Error> {
-    match dir.try_exists() {
+fn ensure_dir(fsys: &dyn Fs, dir: &Path) -> Result<(), io::Error> {
+    match fsys.try_exists(dir) {
         Ok(true) => Ok(()),
-        Ok(false) => fs::create_dir(dir),
+        Ok(false) => fsys.create_dir(dir),
         Err(e) => Err(e),
     }
 }
 
-fn ensure_parent_dir(path: &Path) -> Result<(), Error> {
+fn ensure_parent_dir(fsys: &dyn Fs, path: &Path) -> Result<(), Error> {
     let parent = path.parent().ok_or(Error::Unexpected(format!(
         "Parent dir could not be found: {}",
         path.display()
     )))?;
-    ensure_dir(parent).map_err(Error::Io)
+    ensure_dir(fsys, parent).map_err(Error::Io)
 }
 
 fn to_output_path(src_dir: &Path, output_dir: &Path, input_path: &Path) -> Result<PathBuf, Error> {
@@ -129,61 +171,110 @@ fn init_jinja_env(templates_dir: &Path) -> Environment {
     env
 }
 
-fn render_page(
-    env: &Environment,
-    path: &Path,
-    output_dir: &Path,
-    src_dir: &Path,
-) -> Result<(), Error> {
-    let output_path = to_output_path(src_dir, output_dir, path)?;
-    ensure_parent_dir(&output_path)?;
-    let mut output_file = File::create(output_path).map_err(Error::Io)?;
-    let tmpl_path = path.strip_prefix(src_dir)
-        .map_err(Error::StripPrefix)?
-        .to_string_lossy();
-    let tmpl = env.get_template(&tmpl_path).map_err(Error::Minijinja)?;
-    tmpl.render_to_write(context!(), &mut output_file)
-        .map_err(Error::Minijinja)?;
-    println!("Rendered template to file: {output_file:?}");
+/// A page's source split into the parts needed to render and place it:
+/// its already-parsed front matter and body, and the output path it
+/// resolves to (honouring `page.permalink` if the front matter set one).
+struct PageBuild {
+    output_path: PathBuf,
+    body: String,
+    page_data: Data,
+}
+
+fn prepare_page(fsys: &dyn Fs, path: &Path, output_dir: &Path, src_dir: &Path) -> Result<PageBuild, Error> {
+    let bytes = fsys.read(path).map_err(Error::Io)?;
+    let source = String::from_utf8(bytes).map_err(|e| Error::DataParse {
+        path: path.to_path_buf(),
+        message: e.utf8_error().to_string(),
+    })?;
+    let (front_matter, body) = data::split_front_matter(&source);
+    let page_data = match front_matter {
+        Some(raw) => data::parse_front_matter(path, raw)?,
+        None => Data::empty_table(),
+    };
+    let output_path = match page_data.get("permalink").and_then(Data::as_str) {
+        Some(permalink) => {
+            let relative = permalink.trim_start_matches('/');
+            if Path::new(relative)
+                .components()
+                .any(|c| c == Component::ParentDir)
+            {
+                return Err(Error::InvalidPermalink {
+                    path: path.to_path_buf(),
+                    permalink: permalink.to_string(),
+                });
+            }
+            output_dir.join(relative)
+        }
+        None => to_output_path(src_dir, output_dir, path)?,
+    };
+    Ok(PageBuild {
+        output_path,
+        body: body.to_string(),
+        page_data,
+    })
+}
+
+fn render_page(fsys: &dyn Fs, env: &Environment, build: PageBuild, site_data: &Value) -> Result<(), Error> {
+    let ctx = context! { site => site_data.clone(), page => Value::from(build.page_data) };
+    let rendered = env.render_str(&build.body, ctx).map_err(Error::Minijinja)?;
+    atomic::write_bytes(fsys, &build.output_path, rendered.as_bytes())?;
+    println!("Rendered template to file: {}", build.output_path.display());
     Ok(())
 }
 
-fn copy_dir_recursive(path: &Path, output_dir: &Path, src_dir: &Path) -> Result<(), Error> {
+fn copy_dir_recursive(fsys: &dyn Fs, path: &Path, output_dir: &Path, src_dir: &Path) -> Result<(), Error> {
     let dst = to_output_path(src_dir, output_dir, path)?;
-    ensure_parent_dir(&dst)?;
+    ensure_parent_dir(fsys, &dst)?;
     // @TODO: Remove the following after confirmation
     // fs::create_dir_all(&dst).map_err(Error::Io)?;
-    for entry in fs::read_dir(path).map_err(Error::Io)? {
-        let entry = entry.map_err(Error::Io)?;
-        let ty = entry.file_type().map_err(Error::Io)?;
-        if ty.is_dir() {
-            copy_dir_recursive(&entry.path(), &dst, src_dir)?;
+    for entry in fsys.read_dir(path).map_err(Error::Io)? {
+        if entry.kind == FileKind::Dir {
+            copy_dir_recursive(fsys, &entry.path, &dst, src_dir)?;
         } else {
-            fs::copy(entry.path(), dst.join(entry.file_name())).map_err(Error::Io)?;
+            atomic::copy_file(fsys, &entry.path, &dst.join(entry.file_name()))?;
         }
     }
     println!("Copied dir recursively: {}", dst.display());
     Ok(())
 }
 
-fn copy_file(path: &Path, output_dir: &Path, src_dir: &Path) -> Result<(), Error> {
+fn copy_file(fsys: &dyn Fs, path: &Path, output_dir: &Path, src_dir: &Path) -> Result<(), Error> {
     let dst = to_output_path(src_dir, output_dir, path)?;
-    ensure_parent_dir(&dst)?;
-    fs::copy(path, &dst).map_err(Error::Io)?;
+    atomic::copy_file(fsys, path, &dst)?;
     println!("Copied file: {}", dst.display());
     Ok(())
 }
 
-fn generate_site(src_dir: &Path) -> Result<(), Error> {
+fn generate_site(fsys: &dyn Fs, src_dir: &Path) -> Result<(), Error> {
     let output_dir = src_dir.join("dist");
-    ensure_dir(&output_dir).map_err(Error::Io)?;
+    ensure_dir(fsys, &output_dir).map_err(Error::Io)?;
     let env = init_jinja_env(src_dir);
-    let input_files = get_input_files(&Path::new(src_dir))?;
+    let mut ignores = IgnoreStack::new();
+    let input_files = get_input_files(fsys, src_dir, src_dir, &mut ignores)?;
+
+    let templates = deps::collect_templates(fsys, src_dir);
+    let graph = deps::DepGraph::build(fsys, src_dir, &templates)?;
+    let site_data_path = data::site_data_path(fsys, src_dir);
+    let site_data = Value::from(data::load_site_data(fsys, src_dir)?);
+
     for file in input_files {
         match file {
-            InputFile::Page(path) => render_page(&env, &path, &output_dir, &src_dir)?,
-            InputFile::File(path) => copy_file(&path, &output_dir, &src_dir)?,
-            InputFile::Dir(path) => copy_dir_recursive(&path, &output_dir, &src_dir)?,
+            InputFile::Page(path) => {
+                let build = prepare_page(fsys, &path, &output_dir, src_dir)?;
+                if deps::is_up_to_date(
+                    fsys,
+                    &path,
+                    &graph,
+                    &build.output_path,
+                    site_data_path.as_deref(),
+                ) {
+                    println!("Up to date, skipping: {}", build.output_path.display());
+                } else {
+                    render_page(fsys, &env, build, &site_data)?;
+                }
+            }
+            InputFile::File(path) => copy_file(fsys, &path, &output_dir, src_dir)?,
+            InputFile::Dir(path) => copy_dir_recursive(fsys, &path, &output_dir, src_dir)?,
         }
     }
     Ok(())
@@ -192,7 +283,8 @@ fn generate_site(src_dir: &Path) -> Result<(), Error> {
 fn main() {
     let args: Vec<String> = env::args().collect();
     let src = Path::new(&args[1]);
-    match generate_site(src) {
+    let fsys = RealFs;
+    match generate_site(&fsys, src) {
         Ok(_) => process::exit(0),
         Err(e) => {
             eprintln!("{e}");
@@ -200,3 +292,113 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::FakeFs;
+
+    #[test]
+    fn to_output_path_strips_jinja_extension() {
+        let src_dir = Path::new("/src");
+        let output_dir = Path::new("/src/dist");
+        let input_path = Path::new("/src/page.html.jinja");
+        let output_path = to_output_path(src_dir, output_dir, input_path).unwrap();
+        assert_eq!(output_path, Path::new("/src/dist/page.html"));
+    }
+
+    #[test]
+    fn to_output_path_passes_through_non_jinja() {
+        let src_dir = Path::new("/src");
+        let output_dir = Path::new("/src/dist");
+        let input_path = Path::new("/src/assets/style.css");
+        let output_path = to_output_path(src_dir, output_dir, input_path).unwrap();
+        assert_eq!(output_path, Path::new("/src/dist/assets/style.css"));
+    }
+
+    #[test]
+    fn get_input_files_applies_default_skip_rules() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/src");
+        fsys.add_dir("/src/dist");
+        fsys.add_file("/src/dist/old.html", "stale");
+        fsys.add_file("/src/index.html.jinja", "<h1>hi</h1>");
+        fsys.add_file("/src/_partial.html.jinja", "{{ content }}");
+        fsys.add_file("/src/style.css~", "backup");
+        fsys.add_file("/src/readme.txt", "hello");
+
+        let mut ignores = IgnoreStack::new();
+        let files = get_input_files(&fsys, Path::new("/src"), Path::new("/src"), &mut ignores).unwrap();
+
+        let pages: Vec<&Path> = files
+            .iter()
+            .filter_map(|f| match f {
+                InputFile::Page(p) => Some(p.as_path()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(pages, vec![Path::new("/src/index.html.jinja")]);
+
+        let plain_files: Vec<&Path> = files
+            .iter()
+            .filter_map(|f| match f {
+                InputFile::File(p) => Some(p.as_path()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(plain_files, vec![Path::new("/src/readme.txt")]);
+
+        assert!(!files
+            .iter()
+            .any(|f| matches!(f, InputFile::Dir(p) if p == Path::new("/src/dist"))));
+    }
+
+    #[test]
+    fn get_input_files_classifies_symlinks_by_target() {
+        let fsys = FakeFs::new();
+        fsys.add_dir("/src");
+        fsys.add_dir("/src/real_dir");
+        fsys.add_file("/src/real_file.txt", "hello");
+        fsys.add_symlink("/src/link_to_dir", "/src/real_dir");
+        fsys.add_symlink("/src/link_to_file", "/src/real_file.txt");
+
+        let mut ignores = IgnoreStack::new();
+        let files = get_input_files(&fsys, Path::new("/src"), Path::new("/src"), &mut ignores).unwrap();
+
+        assert!(files
+            .iter()
+            .any(|f| matches!(f, InputFile::Dir(p) if p == Path::new("/src/link_to_dir"))));
+        assert!(files
+            .iter()
+            .any(|f| matches!(f, InputFile::File(p) if p == Path::new("/src/link_to_file"))));
+    }
+
+    #[test]
+    fn prepare_page_errors_on_invalid_utf8_instead_of_mangling_it() {
+        let fsys = FakeFs::new();
+        fsys.add_file("/src/page.html.jinja", vec![0xff, 0xfe, 0xfd]);
+        let result = prepare_page(
+            &fsys,
+            Path::new("/src/page.html.jinja"),
+            Path::new("/src/dist"),
+            Path::new("/src"),
+        );
+        assert!(matches!(result, Err(Error::DataParse { .. })));
+    }
+
+    #[test]
+    fn prepare_page_rejects_a_permalink_that_escapes_the_output_dir() {
+        let fsys = FakeFs::new();
+        fsys.add_file(
+            "/src/page.html.jinja",
+            "---\npermalink = \"../../etc/cron.d/x\"\n---\nbody",
+        );
+        let result = prepare_page(
+            &fsys,
+            Path::new("/src/page.html.jinja"),
+            Path::new("/src/dist"),
+            Path::new("/src"),
+        );
+        assert!(matches!(result, Err(Error::InvalidPermalink { .. })));
+    }
+}